@@ -0,0 +1,451 @@
+// Per-connection client state and the top-level request dispatch loop.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use crate::posix;
+use crate::registry::{self, Global, Object};
+use crate::wire::{Argument, ArgumentType, Header, Message, MessageDesc};
+
+// Wayland protocol constants
+const WL_DISPLAY_ERROR: u16 = 0;
+const WL_DISPLAY_DELETE_ID: u16 = 1;
+const WL_DISPLAY_SYNC: u16 = 0;
+const WL_DISPLAY_GET_REGISTRY: u16 = 1;
+const WL_REGISTRY_BIND: u16 = 0;
+const WL_CALLBACK_DONE: u16 = 0;
+
+// wl_display.error codes (core protocol).
+pub const WL_DISPLAY_ERROR_INVALID_OBJECT: u32 = 0;
+pub const WL_DISPLAY_ERROR_INVALID_METHOD: u32 = 1;
+pub const WL_DISPLAY_ERROR_IMPLEMENTATION: u32 = 3;
+
+// Global object IDs
+pub const DISPLAY_ID: u32 = 1;
+pub const REGISTRY_ID: u32 = 2;
+
+// Global interface names
+pub const WL_COMPOSITOR_NAME: &str = "wl_compositor";
+pub const XDG_WM_BASE_NAME: &str = "xdg_wm_base";
+pub const WL_SHM_NAME: &str = "wl_shm";
+pub const WL_SEAT_NAME: &str = "wl_seat";
+
+// Signatures for the requests this compositor currently understands.
+const SYNC_SIG: MessageDesc = MessageDesc {
+    name: "wl_display.sync",
+    signature: &[ArgumentType::NewId],
+};
+const GET_REGISTRY_SIG: MessageDesc = MessageDesc {
+    name: "wl_display.get_registry",
+    signature: &[ArgumentType::NewId],
+};
+const BIND_SIG: MessageDesc = MessageDesc {
+    name: "wl_registry.bind",
+    signature: &[
+        ArgumentType::Uint,
+        ArgumentType::Str,
+        ArgumentType::Uint,
+        ArgumentType::NewId,
+    ],
+};
+
+pub struct Client {
+    stream: fs::File,
+    objects: HashMap<u32, Object>,
+    globals: HashMap<u32, Global>,
+    next_id: u32,
+    next_serial: u32,
+    pub(crate) surfaces: HashMap<u32, crate::xdg_shell::Surface>,
+    pub(crate) xdg_surfaces: HashMap<u32, crate::xdg_shell::XdgSurface>,
+    pub(crate) toplevels: HashMap<u32, crate::xdg_shell::Toplevel>,
+    pub(crate) shm_pools: HashMap<u32, crate::shm::ShmPool>,
+    pub(crate) shm_buffers: HashMap<u32, crate::shm::ShmBuffer>,
+    pub(crate) seat: crate::seat::SeatState,
+    pub(crate) ping: crate::xdg_shell::PingState,
+    /// Fds received as SCM_RIGHTS ancillary data, in arrival order, not yet
+    /// claimed by a message's `Fd` argument.
+    fd_queue: VecDeque<RawFd>,
+}
+
+impl Client {
+    pub fn new(stream: fs::File) -> Self {
+        let mut objects = HashMap::new();
+        objects.insert(
+            DISPLAY_ID,
+            Object {
+                interface: "wl_display".to_string(),
+            },
+        );
+
+        Self {
+            stream,
+            objects,
+            globals: HashMap::new(),
+            next_id: REGISTRY_ID,
+            next_serial: 0,
+            surfaces: HashMap::new(),
+            xdg_surfaces: HashMap::new(),
+            toplevels: HashMap::new(),
+            shm_pools: HashMap::new(),
+            shm_buffers: HashMap::new(),
+            seat: crate::seat::SeatState::default(),
+            ping: crate::xdg_shell::PingState::default(),
+            fd_queue: VecDeque::new(),
+        }
+    }
+
+    /// Send the client a fresh `xdg_wm_base.ping` if one is due, or
+    /// report whether an already-outstanding one has gone unanswered long
+    /// enough that the connection should be torn down.
+    pub fn tick(&mut self) -> io::Result<bool> {
+        if crate::xdg_shell::ping_timed_out(self) {
+            return Ok(true);
+        }
+        crate::xdg_shell::maybe_ping(self)?;
+        Ok(false)
+    }
+
+    /// Register a server-created object with its interface so future
+    /// requests on `id` route through the dispatch table.
+    pub(crate) fn create_object(&mut self, id: u32, interface: &str) {
+        self.objects.insert(
+            id,
+            Object {
+                interface: interface.to_string(),
+            },
+        );
+        if id >= self.next_id {
+            self.next_id = id + 1;
+        }
+    }
+
+    /// Send an already-built event to the client. If an argument is an
+    /// `Fd` (e.g. `wl_keyboard.keymap`), it travels as SCM_RIGHTS
+    /// ancillary data alongside the message bytes rather than in the body;
+    /// only the first `Fd` argument of a message is attached.
+    pub(crate) fn send_event(&mut self, event: &Message) -> io::Result<()> {
+        let bytes = event.serialize();
+        let fds = event.fds();
+        if let Some(&fd) = fds.first() {
+            posix::send_with_fd(self.stream.as_raw_fd(), &bytes, Some(fd))?;
+            Ok(())
+        } else {
+            self.stream.write_all(&bytes)
+        }
+    }
+
+    /// Allocate the next monotonically increasing configure serial.
+    pub(crate) fn next_serial(&mut self) -> u32 {
+        self.next_serial += 1;
+        self.next_serial
+    }
+
+    /// Forget a server-side object and tell the client it can recycle the
+    /// id, via `wl_display.delete_id`.
+    pub(crate) fn destroy_object(&mut self, id: u32) -> io::Result<()> {
+        self.objects.remove(&id);
+        self.send_event(&Message {
+            sender_id: DISPLAY_ID,
+            opcode: WL_DISPLAY_DELETE_ID,
+            args: vec![Argument::Uint(id)],
+        })
+    }
+
+    /// Report a fatal protocol error to the client via `wl_display.error`
+    /// and return an `io::Error` for the caller to propagate, which
+    /// disconnects the connection — the Wayland spec treats any protocol
+    /// error as fatal.
+    pub(crate) fn protocol_error(&mut self, object_id: u32, code: u32, message: String) -> io::Error {
+        let _ = self.send_event(&Message {
+            sender_id: DISPLAY_ID,
+            opcode: WL_DISPLAY_ERROR,
+            args: vec![
+                Argument::Object(object_id),
+                Argument::Uint(code),
+                Argument::Str(message.clone()),
+            ],
+        });
+        io::Error::other(message)
+    }
+
+    /// Fill `buf` completely via `recvmsg`, pushing any fd that arrives as
+    /// SCM_RIGHTS ancillary data onto `self.fd_queue`. Returns `Ok(false)`
+    /// if the client disconnected before any bytes were read.
+    fn recv_exact_with_fds(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let raw_fd = self.stream.as_raw_fd();
+        let mut filled = 0;
+        while filled < buf.len() {
+            let (n, fd) = match posix::recv_with_fd(raw_fd, &mut buf[filled..]) {
+                Ok(result) => result,
+                // The ping timer's SO_RCVTIMEO means a read can legitimately
+                // time out partway through a message that's still arriving —
+                // that's not a disconnect or a malformed client, just an
+                // ordinary stream boundary. Only surface it to the caller
+                // (who will treat it as "nothing to read yet") when no
+                // partial message is in flight; otherwise keep waiting for
+                // the rest instead of desyncing the header/body framing.
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && filled > 0 => continue,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                if filled == 0 {
+                    return Ok(false);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed mid-message",
+                ));
+            }
+            if let Some(fd) = fd {
+                self.fd_queue.push_back(fd);
+            }
+            filled += n;
+        }
+        Ok(true)
+    }
+
+    pub fn handle_message(&mut self) -> Result<bool, std::io::Error> {
+        // Read message header object id, size, opcode
+        let mut header = [0u8; crate::wire::HEADER_LEN];
+        if !self.recv_exact_with_fds(&mut header)? {
+            return Ok(false); // Client disconnected
+        }
+
+        let header = Header::parse(&header);
+        let obj_id = header.sender_id;
+        let opcode = header.opcode;
+        let body_size = header.body_len().map_err(|message| {
+            self.protocol_error(obj_id, WL_DISPLAY_ERROR_IMPLEMENTATION, message)
+        })?;
+
+        // Read message body. The header is already consumed, so a receive
+        // timeout here is mid-message: keep waiting rather than desync.
+        let mut body = vec![0u8; body_size];
+        while body_size > 0 {
+            match self.recv_exact_with_fds(&mut body) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+
+        // Fds for this message, if any, were queued up by the reads above.
+        let mut fds = std::mem::take(&mut self.fd_queue);
+
+        // Handle message by object id and opcode
+        let result = (|| -> io::Result<()> {
+            match (obj_id, opcode) {
+                (DISPLAY_ID, WL_DISPLAY_SYNC) => {
+                    let msg = Message::deserialize(obj_id, opcode, &body, &SYNC_SIG, &mut fds)?;
+                    let callback_id = match msg.args[0] {
+                        Argument::NewId(id) => id,
+                        _ => unreachable!(),
+                    };
+                    self.create_object(callback_id, "wl_callback");
+
+                    // Send callback done event (arg0 = timestamp)
+                    let done = Message {
+                        sender_id: callback_id,
+                        opcode: WL_CALLBACK_DONE,
+                        args: vec![Argument::Uint(0)],
+                    };
+                    self.send_event(&done)?;
+                }
+                (DISPLAY_ID, WL_DISPLAY_GET_REGISTRY) => {
+                    let msg =
+                        Message::deserialize(obj_id, opcode, &body, &GET_REGISTRY_SIG, &mut fds)?;
+                    let registry_id = match msg.args[0] {
+                        Argument::NewId(id) => id,
+                        _ => unreachable!(),
+                    };
+                    self.create_object(registry_id, "wl_registry");
+
+                    // Send registry global events
+                    self.send_global_event(registry_id, 1, WL_COMPOSITOR_NAME, 4)?;
+                    self.send_global_event(registry_id, 2, XDG_WM_BASE_NAME, 3)?;
+                    self.send_global_event(registry_id, 3, WL_SHM_NAME, 1)?;
+                    self.send_global_event(registry_id, 4, WL_SEAT_NAME, 7)?;
+                }
+                (id, WL_REGISTRY_BIND)
+                    if self.objects.get(&id).map(|o| o.interface.as_str())
+                        == Some("wl_registry") =>
+                {
+                    let msg = Message::deserialize(obj_id, opcode, &body, &BIND_SIG, &mut fds)?;
+                    let (name, interface, version, new_id) =
+                        match (&msg.args[0], &msg.args[1], &msg.args[2], &msg.args[3]) {
+                            (
+                                Argument::Uint(name),
+                                Argument::Str(interface),
+                                Argument::Uint(version),
+                                Argument::NewId(new_id),
+                            ) => (*name, interface.clone(), *version, *new_id),
+                            _ => unreachable!(),
+                        };
+                    self.bind(name, &interface, version, new_id)?;
+                }
+                (id, opcode) => {
+                    let Some(interface) = self.objects.get(&id).map(|o| o.interface.clone())
+                    else {
+                        return Err(self.protocol_error(
+                            id,
+                            WL_DISPLAY_ERROR_INVALID_OBJECT,
+                            format!("object {} does not exist", id),
+                        ));
+                    };
+                    match registry::lookup(&interface, opcode) {
+                        Some(handler) => {
+                            handler(self, id, opcode, &body, &mut fds)?;
+                        }
+                        None => {
+                            return Err(self.protocol_error(
+                                id,
+                                WL_DISPLAY_ERROR_INVALID_METHOD,
+                                format!("{} has no method {}", interface, opcode),
+                            ));
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })();
+
+        // Anything left unclaimed stays queued for the next message.
+        self.fd_queue = fds;
+        result?;
+
+        Ok(true)
+    }
+
+    /// Handle `wl_registry.bind`: validate the requested name/interface/
+    /// version against what was advertised, then create the new object so
+    /// subsequent requests on `new_id` route through the dispatch table.
+    fn bind(&mut self, name: u32, interface: &str, version: u32, new_id: u32) -> io::Result<()> {
+        let Some(global) = self.globals.get(&name).cloned() else {
+            return Err(self.protocol_error(
+                REGISTRY_ID,
+                WL_DISPLAY_ERROR_INVALID_OBJECT,
+                format!("bind: no such global name {}", name),
+            ));
+        };
+        if global.interface != interface {
+            return Err(self.protocol_error(
+                REGISTRY_ID,
+                WL_DISPLAY_ERROR_INVALID_METHOD,
+                format!(
+                    "bind: name {} is {}, not {}",
+                    name, global.interface, interface
+                ),
+            ));
+        }
+        if version == 0 || version > global.version {
+            return Err(self.protocol_error(
+                REGISTRY_ID,
+                WL_DISPLAY_ERROR_INVALID_METHOD,
+                format!(
+                    "bind: requested version {} exceeds advertised version {} for {}",
+                    version, global.version, interface
+                ),
+            ));
+        }
+
+        self.create_object(new_id, interface);
+
+        if interface == WL_SHM_NAME {
+            crate::shm::send_formats(self, new_id)?;
+        }
+        if interface == WL_SEAT_NAME {
+            crate::seat::send_capabilities(self, new_id)?;
+        }
+        if interface == XDG_WM_BASE_NAME {
+            self.ping.wm_base = Some(new_id);
+        }
+        Ok(())
+    }
+
+    fn send_global_event(
+        &mut self,
+        registry_id: u32,
+        name: u32,
+        interface: &str,
+        version: u32,
+    ) -> Result<(), std::io::Error> {
+        self.globals.insert(
+            name,
+            Global {
+                interface: interface.to_string(),
+                version,
+            },
+        );
+
+        let event = Message {
+            sender_id: registry_id,
+            opcode: 0, // wl_registry.global
+            args: vec![
+                Argument::Uint(name),
+                Argument::Str(interface.to_string()),
+                Argument::Uint(version),
+            ],
+        };
+        self.send_event(&event)
+    }
+}
+
+impl Drop for Client {
+    /// Close out any fds that arrived as SCM_RIGHTS ancillary data but were
+    /// never claimed by a message's `Fd` argument (a stray extra fd, or one
+    /// attached to a request that errored before reaching it) — otherwise
+    /// they'd leak for the life of the process.
+    fn drop(&mut self) {
+        for fd in self.fd_queue.drain(..) {
+            drop(unsafe { fs::File::from_raw_fd(fd) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    /// A `Client` wired to one end of a socket pair so `send_event` (and
+    /// anything that calls it, like `protocol_error`) has somewhere to
+    /// write. The other end is returned too and must be kept alive for
+    /// the duration of the test, or writes fail with a broken pipe.
+    fn test_client() -> (Client, UnixStream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        (Client::new(unsafe { fs::File::from_raw_fd(a.into_raw_fd()) }), b)
+    }
+
+    #[test]
+    fn bind_rejects_unknown_global_name() {
+        let (mut client, _peer) = test_client();
+        assert!(client.bind(999, WL_COMPOSITOR_NAME, 1, 100).is_err());
+    }
+
+    #[test]
+    fn bind_rejects_interface_mismatch() {
+        let (mut client, _peer) = test_client();
+        client.send_global_event(REGISTRY_ID, 1, WL_COMPOSITOR_NAME, 4).unwrap();
+        assert!(client.bind(1, WL_SHM_NAME, 1, 100).is_err());
+    }
+
+    #[test]
+    fn bind_rejects_version_above_advertised() {
+        let (mut client, _peer) = test_client();
+        client.send_global_event(REGISTRY_ID, 1, WL_COMPOSITOR_NAME, 4).unwrap();
+        assert!(client.bind(1, WL_COMPOSITOR_NAME, 5, 100).is_err());
+    }
+
+    #[test]
+    fn bind_accepts_matching_request() {
+        let (mut client, _peer) = test_client();
+        client.send_global_event(REGISTRY_ID, 1, WL_COMPOSITOR_NAME, 4).unwrap();
+        assert!(client.bind(1, WL_COMPOSITOR_NAME, 4, 100).is_ok());
+        assert!(client.objects.contains_key(&100));
+    }
+}
@@ -0,0 +1,220 @@
+// A handful of POSIX primitives this compositor needs that aren't
+// otherwise reachable through `std`: receiving a file descriptor passed
+// as SCM_RIGHTS ancillary data, and mmap'ing one. There's no vendored
+// `libc` dependency in this tree, so the few items needed are declared
+// directly rather than pulled in wholesale.
+//
+// The fd these run against is the `chan:` scheme fd `main.rs` gets back
+// from `syscall::dup(listener, b"listen")` — Redox's two-way channel
+// primitive, the same role a UNIX domain socket plays on Linux, including
+// fd-passing support. relibc implements the standard `recvmsg`/`sendmsg`/
+// `SCM_RIGHTS` surface against it, which is why these are plain POSIX
+// calls rather than something built on the `syscall` crate directly.
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::ptr;
+
+#[repr(C)]
+struct Iovec {
+    iov_base: *mut c_void,
+    iov_len: usize,
+}
+
+#[repr(C)]
+struct Msghdr {
+    msg_name: *mut c_void,
+    msg_namelen: u32,
+    msg_iov: *mut Iovec,
+    msg_iovlen: usize,
+    msg_control: *mut c_void,
+    msg_controllen: usize,
+    msg_flags: c_int,
+}
+
+#[repr(C)]
+struct Cmsghdr {
+    cmsg_len: usize,
+    cmsg_level: c_int,
+    cmsg_type: c_int,
+}
+
+const SOL_SOCKET: c_int = 1;
+const SCM_RIGHTS: c_int = 1;
+const SO_RCVTIMEO: c_int = 20;
+
+const PROT_READ: c_int = 1;
+const MAP_SHARED: c_int = 1;
+
+#[repr(C)]
+struct Timeval {
+    tv_sec: i64,
+    tv_usec: i64,
+}
+
+extern "C" {
+    fn recvmsg(fd: c_int, msg: *mut Msghdr, flags: c_int) -> isize;
+    fn sendmsg(fd: c_int, msg: *const Msghdr, flags: c_int) -> isize;
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn memfd_create(name: *const c_char, flags: c_uint) -> c_int;
+    fn setsockopt(
+        fd: c_int,
+        level: c_int,
+        optname: c_int,
+        optval: *const c_void,
+        optlen: u32,
+    ) -> c_int;
+}
+
+fn cmsg_align(len: usize) -> usize {
+    let word = mem::size_of::<usize>();
+    (len + word - 1) & !(word - 1)
+}
+
+/// Receive up to `buf.len()` bytes on `fd`, returning the number of bytes
+/// read and a file descriptor if one arrived as SCM_RIGHTS ancillary data
+/// alongside this read (`wl_shm.create_pool` passes its pool fd this way).
+pub fn recv_with_fd(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, Option<RawFd>)> {
+    let mut iov = Iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+
+    let cmsg_space = cmsg_align(mem::size_of::<Cmsghdr>()) + cmsg_align(mem::size_of::<RawFd>());
+    let mut control = vec![0u8; cmsg_space];
+
+    let mut msg = Msghdr {
+        msg_name: ptr::null_mut(),
+        msg_namelen: 0,
+        msg_iov: &mut iov,
+        msg_iovlen: 1,
+        msg_control: control.as_mut_ptr() as *mut c_void,
+        msg_controllen: control.len(),
+        msg_flags: 0,
+    };
+
+    let n = unsafe { recvmsg(fd, &mut msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut passed_fd = None;
+    if msg.msg_controllen >= mem::size_of::<Cmsghdr>() {
+        let cmsg = unsafe { &*(control.as_ptr() as *const Cmsghdr) };
+        if cmsg.cmsg_level == SOL_SOCKET && cmsg.cmsg_type == SCM_RIGHTS {
+            let data = unsafe { (cmsg as *const Cmsghdr).add(1) as *const c_int };
+            passed_fd = Some(unsafe { *data } as RawFd);
+        }
+    }
+
+    Ok((n as usize, passed_fd))
+}
+
+/// Send `buf`, optionally attaching `passed_fd` as SCM_RIGHTS ancillary
+/// data (used for `wl_keyboard.keymap`, whose fd the wire layer itself
+/// never puts in the message body — see `Message::fds`).
+pub fn send_with_fd(fd: RawFd, buf: &[u8], passed_fd: Option<RawFd>) -> io::Result<usize> {
+    let mut iov = Iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+
+    let cmsg_space = cmsg_align(mem::size_of::<Cmsghdr>()) + cmsg_align(mem::size_of::<RawFd>());
+    let mut control = vec![0u8; cmsg_space];
+    let mut controllen = 0usize;
+
+    if let Some(passed_fd) = passed_fd {
+        controllen = control.len();
+        let cmsg = unsafe { &mut *(control.as_mut_ptr() as *mut Cmsghdr) };
+        cmsg.cmsg_len = cmsg_align(mem::size_of::<Cmsghdr>()) + mem::size_of::<RawFd>();
+        cmsg.cmsg_level = SOL_SOCKET;
+        cmsg.cmsg_type = SCM_RIGHTS;
+        let data = unsafe { (cmsg as *mut Cmsghdr).add(1) as *mut c_int };
+        unsafe { *data = passed_fd };
+    }
+
+    let msg = Msghdr {
+        msg_name: ptr::null_mut(),
+        msg_namelen: 0,
+        msg_iov: &mut iov,
+        msg_iovlen: 1,
+        msg_control: if controllen == 0 {
+            ptr::null_mut()
+        } else {
+            control.as_mut_ptr() as *mut c_void
+        },
+        msg_controllen: controllen,
+        msg_flags: 0,
+    };
+
+    let n = unsafe { sendmsg(fd, &msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Create an anonymous, in-memory file of `size` bytes, suitable for
+/// handing a client read-only content (e.g. an XKB keymap) via fd passing.
+pub fn create_memfd(name: &str, size: usize) -> io::Result<RawFd> {
+    let c_name = CString::new(name).expect("memfd name must not contain a NUL byte");
+    let fd = unsafe { memfd_create(c_name.as_ptr(), 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+    file.set_len(size as u64)?;
+    std::mem::forget(file); // ownership moves to the caller
+    Ok(fd)
+}
+
+/// Map `len` bytes of `fd` read-only and shared — the access pattern a
+/// `wl_shm_pool` needs to read pixels the client wrote.
+pub fn mmap_shared_readonly(fd: RawFd, len: usize) -> io::Result<*mut u8> {
+    let ptr = unsafe { mmap(ptr::null_mut(), len, PROT_READ, MAP_SHARED, fd, 0) };
+    if ptr as isize == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ptr as *mut u8)
+}
+
+/// Undo `mmap_shared_readonly`.
+pub fn munmap_region(ptr: *mut u8, len: usize) {
+    unsafe {
+        munmap(ptr as *mut c_void, len);
+    }
+}
+
+/// Bound how long a blocking `recvmsg` on `fd` will wait for data. Used so
+/// the per-client loop wakes up periodically even when the client is idle,
+/// to drive the xdg_wm_base ping timer.
+pub fn set_recv_timeout(fd: RawFd, secs: i64) -> io::Result<()> {
+    let tv = Timeval {
+        tv_sec: secs,
+        tv_usec: 0,
+    };
+    let rc = unsafe {
+        setsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_RCVTIMEO,
+            &tv as *const Timeval as *const c_void,
+            mem::size_of::<Timeval>() as u32,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
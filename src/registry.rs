@@ -0,0 +1,104 @@
+// The server-side object/global registry and the request dispatch table.
+//
+// Every object a client can address is stamped with its interface when it
+// is created: globals through `wl_registry.bind`, everything else (surfaces,
+// pools, buffers, ...) by the request that creates it. From then on, requests on
+// that object are routed by `(interface, opcode)` through the dispatch
+// table below instead of growing the `match (obj_id, opcode)` in
+// `Client::handle_message`.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::OnceLock;
+
+use crate::client::Client;
+use crate::wire::MessageDesc;
+
+/// A request handler for a bound object: given the object id the request
+/// was sent to, the opcode, and the raw (undecoded) body, it deserializes
+/// its own arguments and acts on them.
+pub type Handler = fn(&mut Client, u32, u16, &[u8], &mut VecDeque<RawFd>) -> io::Result<()>;
+
+/// A global advertised to the client via `wl_registry.global`, remembered
+/// (keyed by its name) so a later `bind` can be validated against it.
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub interface: String,
+    pub version: u32,
+}
+
+/// A server-side object created for a client, keyed by its id.
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub interface: String,
+}
+
+static DISPATCH_TABLE: OnceLock<HashMap<(String, u16), Handler>> = OnceLock::new();
+
+/// The `(interface, opcode) -> Handler` table. Interfaces register their
+/// request handlers here as they gain support; a request with no entry
+/// makes `handle_message` send a fatal `wl_display.error` (invalid_method).
+pub fn dispatch_table() -> &'static HashMap<(String, u16), Handler> {
+    DISPATCH_TABLE.get_or_init(build_dispatch_table)
+}
+
+fn build_dispatch_table() -> HashMap<(String, u16), Handler> {
+    let mut table = HashMap::new();
+    crate::xdg_shell::register(&mut table);
+    crate::shm::register(&mut table);
+    crate::seat::register(&mut table);
+
+    // `destroy` is opcode 0 on every interface below except wl_shm_pool,
+    // where create_buffer already claims opcode 0.
+    for interface in ["wl_surface", "xdg_surface", "xdg_toplevel", "wl_buffer"] {
+        table.insert((interface.to_string(), 0), generic_destroy);
+    }
+    table.insert(("wl_shm_pool".to_string(), 1), generic_destroy);
+
+    table
+}
+
+const DESTROY_SIG: MessageDesc = MessageDesc {
+    name: "destroy",
+    signature: &[],
+};
+
+/// Shared `destroy` handler for the object kinds that don't otherwise need
+/// to react to their own destruction beyond dropping whatever per-type
+/// state they hold (e.g. a `wl_shm_pool`'s mapping) and letting the client
+/// recycle the id.
+fn generic_destroy(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    crate::wire::Message::deserialize(sender_id, opcode, body, &DESTROY_SIG, fds)?;
+
+    // Clear the parent's back-reference so it doesn't keep pointing at an id
+    // the client is now free to reuse for something unrelated.
+    if let Some(xdg_surface) = client.xdg_surfaces.remove(&sender_id) {
+        if let Some(surface) = client.surfaces.get_mut(&xdg_surface.wl_surface) {
+            surface.xdg_surface = None;
+        }
+    }
+    if let Some(toplevel) = client.toplevels.remove(&sender_id) {
+        if let Some(xdg_surface) = client.xdg_surfaces.get_mut(&toplevel.xdg_surface) {
+            xdg_surface.toplevel = None;
+        }
+    }
+    client.surfaces.remove(&sender_id);
+    client.shm_buffers.remove(&sender_id);
+    client.shm_pools.remove(&sender_id); // drops the mapping via ShmPool::drop
+    client.destroy_object(sender_id)
+}
+
+/// Look up the handler for a request sent to an object bound as
+/// `interface`, if any.
+pub fn lookup(interface: &str, opcode: u16) -> Option<Handler> {
+    dispatch_table()
+        .get(&(interface.to_string(), opcode))
+        .copied()
+}
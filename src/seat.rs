@@ -0,0 +1,444 @@
+// wl_seat: pointer and keyboard input, delivered to whichever surface
+// currently has pointer/keyboard focus.
+//
+// There's no input backend wired up yet (no libinput/DRM integration in
+// this tree), so `pointer_*`/`keyboard_*` below are the delivery API a
+// future input source will drive; this commit covers the protocol side —
+// object creation, the keymap handoff, and focus-gated event delivery.
+// `pointer_enter`/`keyboard_enter` already have a real caller (xdg_shell's
+// initial-commit focus handoff); the rest of the delivery API is only
+// exercised by this file's own tests until that input source exists.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+
+use crate::client::Client;
+use crate::posix;
+use crate::registry::Handler;
+use crate::wire::{Argument, ArgumentType, Message, MessageDesc};
+
+const WL_SEAT_CAPABILITIES: u16 = 0;
+const WL_SEAT_GET_POINTER: u16 = 0;
+const WL_SEAT_GET_KEYBOARD: u16 = 1;
+
+const WL_POINTER_ENTER: u16 = 0;
+const WL_POINTER_LEAVE: u16 = 1;
+const WL_POINTER_MOTION: u16 = 2;
+const WL_POINTER_BUTTON: u16 = 3;
+const WL_POINTER_FRAME: u16 = 5;
+
+const WL_KEYBOARD_KEYMAP: u16 = 0;
+const WL_KEYBOARD_ENTER: u16 = 1;
+const WL_KEYBOARD_LEAVE: u16 = 2;
+const WL_KEYBOARD_KEY: u16 = 3;
+const WL_KEYBOARD_MODIFIERS: u16 = 4;
+
+const WL_KEYBOARD_KEYMAP_FORMAT_XKB_V1: u32 = 1;
+
+const SEAT_CAPABILITY_POINTER: u32 = 1;
+const SEAT_CAPABILITY_KEYBOARD: u32 = 2;
+
+const GET_POINTER_SIG: MessageDesc = MessageDesc {
+    name: "wl_seat.get_pointer",
+    signature: &[ArgumentType::NewId],
+};
+const GET_KEYBOARD_SIG: MessageDesc = MessageDesc {
+    name: "wl_seat.get_keyboard",
+    signature: &[ArgumentType::NewId],
+};
+
+// Equivalent to the default `pc+us+inet(evdev)` keymap libxkbcommon would
+// compile; written out directly since this tree has no xkbcommon binding.
+const DEFAULT_KEYMAP: &str = concat!(
+    "xkb_keymap {\n",
+    "  xkb_keycodes  { include \"evdev+aliases(qwerty)\" };\n",
+    "  xkb_types     { include \"complete\" };\n",
+    "  xkb_compat    { include \"complete\" };\n",
+    "  xkb_symbols   { include \"pc+us+inet(evdev)\" };\n",
+    "};\n",
+    "\0",
+);
+
+/// Per-client seat state: the bound pointer/keyboard objects, if any, and
+/// which surface currently has each kind of focus.
+#[derive(Default)]
+pub struct SeatState {
+    pub pointer: Option<u32>,
+    pub keyboard: Option<u32>,
+    pub pointer_focus: Option<u32>,
+    pub keyboard_focus: Option<u32>,
+}
+
+/// Register this module's request handlers into the shared dispatch table.
+pub fn register(table: &mut HashMap<(String, u16), Handler>) {
+    table.insert(("wl_seat".to_string(), WL_SEAT_GET_POINTER), get_pointer);
+    table.insert(("wl_seat".to_string(), WL_SEAT_GET_KEYBOARD), get_keyboard);
+}
+
+/// Send the `wl_seat.capabilities` event a newly bound seat must receive.
+pub fn send_capabilities(client: &mut Client, seat_id: u32) -> io::Result<()> {
+    client.send_event(&Message {
+        sender_id: seat_id,
+        opcode: WL_SEAT_CAPABILITIES,
+        args: vec![Argument::Uint(
+            SEAT_CAPABILITY_POINTER | SEAT_CAPABILITY_KEYBOARD,
+        )],
+    })
+}
+
+fn get_pointer(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &GET_POINTER_SIG, fds)?;
+    let new_id = match msg.args[0] {
+        Argument::NewId(id) => id,
+        _ => unreachable!(),
+    };
+    client.create_object(new_id, "wl_pointer");
+    client.seat.pointer = Some(new_id);
+    Ok(())
+}
+
+fn get_keyboard(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &GET_KEYBOARD_SIG, fds)?;
+    let new_id = match msg.args[0] {
+        Argument::NewId(id) => id,
+        _ => unreachable!(),
+    };
+    client.create_object(new_id, "wl_keyboard");
+    client.seat.keyboard = Some(new_id);
+    send_keymap(client, new_id)
+}
+
+fn send_keymap(client: &mut Client, keyboard_id: u32) -> io::Result<()> {
+    let contents = DEFAULT_KEYMAP.as_bytes();
+    let fd = posix::create_memfd("wayland-keymap", contents.len())?;
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    file.write_all(contents)?;
+
+    client.send_event(&Message {
+        sender_id: keyboard_id,
+        opcode: WL_KEYBOARD_KEYMAP,
+        args: vec![
+            Argument::Uint(WL_KEYBOARD_KEYMAP_FORMAT_XKB_V1),
+            Argument::Fd(fd),
+            Argument::Uint(contents.len() as u32),
+        ],
+    })
+    // `file` drops here, closing our copy; the client received its own
+    // duplicate of the fd via the sendmsg call above.
+}
+
+/// Give pointer focus to `surface_id`, sending `leave` to the previous
+/// focus (if any) and `enter` + `frame` to the new one, in surface-local
+/// coordinates.
+pub fn pointer_enter(client: &mut Client, surface_id: u32, x: i32, y: i32) -> io::Result<()> {
+    let Some(pointer) = client.seat.pointer else {
+        return Ok(());
+    };
+    if client.seat.pointer_focus == Some(surface_id) {
+        return pointer_motion(client, 0, x, y);
+    }
+
+    if let Some(previous) = client.seat.pointer_focus.take() {
+        let serial = client.next_serial();
+        client.send_event(&Message {
+            sender_id: pointer,
+            opcode: WL_POINTER_LEAVE,
+            args: vec![Argument::Uint(serial), Argument::Object(previous)],
+        })?;
+    }
+
+    client.seat.pointer_focus = Some(surface_id);
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: pointer,
+        opcode: WL_POINTER_ENTER,
+        args: vec![
+            Argument::Uint(serial),
+            Argument::Object(surface_id),
+            Argument::Fixed(fixed_from_int(x)),
+            Argument::Fixed(fixed_from_int(y)),
+        ],
+    })?;
+    send_pointer_frame(client)
+}
+
+/// Clear pointer focus, sending `leave` if a surface currently has it.
+pub fn pointer_leave(client: &mut Client) -> io::Result<()> {
+    let Some(pointer) = client.seat.pointer else {
+        return Ok(());
+    };
+    let Some(surface_id) = client.seat.pointer_focus.take() else {
+        return Ok(());
+    };
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: pointer,
+        opcode: WL_POINTER_LEAVE,
+        args: vec![Argument::Uint(serial), Argument::Object(surface_id)],
+    })?;
+    send_pointer_frame(client)
+}
+
+/// Report pointer motion within the currently focused surface.
+pub fn pointer_motion(client: &mut Client, time: u32, x: i32, y: i32) -> io::Result<()> {
+    let Some(pointer) = client.seat.pointer else {
+        return Ok(());
+    };
+    if client.seat.pointer_focus.is_none() {
+        return Ok(());
+    }
+    client.send_event(&Message {
+        sender_id: pointer,
+        opcode: WL_POINTER_MOTION,
+        args: vec![
+            Argument::Uint(time),
+            Argument::Fixed(fixed_from_int(x)),
+            Argument::Fixed(fixed_from_int(y)),
+        ],
+    })?;
+    send_pointer_frame(client)
+}
+
+/// Report a button press/release on the currently focused surface.
+pub fn pointer_button(client: &mut Client, time: u32, button: u32, pressed: bool) -> io::Result<()> {
+    let Some(pointer) = client.seat.pointer else {
+        return Ok(());
+    };
+    if client.seat.pointer_focus.is_none() {
+        return Ok(());
+    }
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: pointer,
+        opcode: WL_POINTER_BUTTON,
+        args: vec![
+            Argument::Uint(serial),
+            Argument::Uint(time),
+            Argument::Uint(button),
+            Argument::Uint(pressed as u32),
+        ],
+    })?;
+    send_pointer_frame(client)
+}
+
+fn send_pointer_frame(client: &mut Client) -> io::Result<()> {
+    let Some(pointer) = client.seat.pointer else {
+        return Ok(());
+    };
+    client.send_event(&Message {
+        sender_id: pointer,
+        opcode: WL_POINTER_FRAME,
+        args: vec![],
+    })
+}
+
+/// Give keyboard focus to `surface_id`, sending `leave` to the previous
+/// focus (if any) and `enter` to the new one.
+pub fn keyboard_enter(client: &mut Client, surface_id: u32) -> io::Result<()> {
+    let Some(keyboard) = client.seat.keyboard else {
+        return Ok(());
+    };
+    if client.seat.keyboard_focus == Some(surface_id) {
+        return Ok(());
+    }
+
+    if let Some(previous) = client.seat.keyboard_focus.take() {
+        let serial = client.next_serial();
+        client.send_event(&Message {
+            sender_id: keyboard,
+            opcode: WL_KEYBOARD_LEAVE,
+            args: vec![Argument::Uint(serial), Argument::Object(previous)],
+        })?;
+    }
+
+    client.seat.keyboard_focus = Some(surface_id);
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: keyboard,
+        opcode: WL_KEYBOARD_ENTER,
+        args: vec![
+            Argument::Uint(serial),
+            Argument::Object(surface_id),
+            Argument::Array(Vec::new()), // no keys currently held
+        ],
+    })
+}
+
+/// Clear keyboard focus, sending `leave` if a surface currently has it.
+pub fn keyboard_leave(client: &mut Client) -> io::Result<()> {
+    let Some(keyboard) = client.seat.keyboard else {
+        return Ok(());
+    };
+    let Some(surface_id) = client.seat.keyboard_focus.take() else {
+        return Ok(());
+    };
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: keyboard,
+        opcode: WL_KEYBOARD_LEAVE,
+        args: vec![Argument::Uint(serial), Argument::Object(surface_id)],
+    })
+}
+
+/// Report a key press/release to whichever surface currently has keyboard
+/// focus.
+pub fn keyboard_key(client: &mut Client, time: u32, key: u32, pressed: bool) -> io::Result<()> {
+    let Some(keyboard) = client.seat.keyboard else {
+        return Ok(());
+    };
+    if client.seat.keyboard_focus.is_none() {
+        return Ok(());
+    }
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: keyboard,
+        opcode: WL_KEYBOARD_KEY,
+        args: vec![
+            Argument::Uint(serial),
+            Argument::Uint(time),
+            Argument::Uint(key),
+            Argument::Uint(pressed as u32),
+        ],
+    })
+}
+
+/// Report an updated modifier mask to whichever surface currently has
+/// keyboard focus.
+pub fn keyboard_modifiers(
+    client: &mut Client,
+    mods_depressed: u32,
+    mods_latched: u32,
+    mods_locked: u32,
+    group: u32,
+) -> io::Result<()> {
+    let Some(keyboard) = client.seat.keyboard else {
+        return Ok(());
+    };
+    if client.seat.keyboard_focus.is_none() {
+        return Ok(());
+    }
+    let serial = client.next_serial();
+    client.send_event(&Message {
+        sender_id: keyboard,
+        opcode: WL_KEYBOARD_MODIFIERS,
+        args: vec![
+            Argument::Uint(serial),
+            Argument::Uint(mods_depressed),
+            Argument::Uint(mods_latched),
+            Argument::Uint(mods_locked),
+            Argument::Uint(group),
+        ],
+    })
+}
+
+fn fixed_from_int(v: i32) -> i32 {
+    v * 256
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    fn test_client() -> (Client, UnixStream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let a = unsafe { std::fs::File::from_raw_fd(a.into_raw_fd()) };
+        (Client::new(a), b)
+    }
+
+    #[test]
+    fn pointer_enter_leaves_focus_unset_without_a_pointer() {
+        let (mut client, _peer) = test_client();
+        // No wl_pointer bound yet: should be a silent no-op, not an error.
+        assert!(pointer_enter(&mut client, 5, 0, 0).is_ok());
+        assert_eq!(client.seat.pointer_focus, None);
+    }
+
+    #[test]
+    fn pointer_enter_moves_focus_from_previous_surface() {
+        let (mut client, _peer) = test_client();
+        client.seat.pointer = Some(99);
+
+        pointer_enter(&mut client, 1, 10, 20).unwrap();
+        assert_eq!(client.seat.pointer_focus, Some(1));
+
+        pointer_enter(&mut client, 2, 0, 0).unwrap();
+        assert_eq!(client.seat.pointer_focus, Some(2));
+    }
+
+    #[test]
+    fn pointer_leave_clears_focus() {
+        let (mut client, _peer) = test_client();
+        client.seat.pointer = Some(99);
+        pointer_enter(&mut client, 1, 0, 0).unwrap();
+
+        pointer_leave(&mut client).unwrap();
+        assert_eq!(client.seat.pointer_focus, None);
+    }
+
+    #[test]
+    fn pointer_motion_and_button_require_focus() {
+        let (mut client, _peer) = test_client();
+        client.seat.pointer = Some(99);
+
+        // No focused surface yet: no-ops.
+        assert!(pointer_motion(&mut client, 0, 1, 1).is_ok());
+        assert!(pointer_button(&mut client, 0, 0, true).is_ok());
+
+        pointer_enter(&mut client, 1, 0, 0).unwrap();
+        assert!(pointer_motion(&mut client, 1, 2, 3).is_ok());
+        assert!(pointer_button(&mut client, 1, 0, true).is_ok());
+    }
+
+    #[test]
+    fn keyboard_enter_moves_focus_from_previous_surface() {
+        let (mut client, _peer) = test_client();
+        client.seat.keyboard = Some(99);
+
+        keyboard_enter(&mut client, 1).unwrap();
+        assert_eq!(client.seat.keyboard_focus, Some(1));
+
+        keyboard_enter(&mut client, 2).unwrap();
+        assert_eq!(client.seat.keyboard_focus, Some(2));
+    }
+
+    #[test]
+    fn keyboard_leave_clears_focus() {
+        let (mut client, _peer) = test_client();
+        client.seat.keyboard = Some(99);
+        keyboard_enter(&mut client, 1).unwrap();
+
+        keyboard_leave(&mut client).unwrap();
+        assert_eq!(client.seat.keyboard_focus, None);
+    }
+
+    #[test]
+    fn keyboard_key_and_modifiers_require_focus() {
+        let (mut client, _peer) = test_client();
+        client.seat.keyboard = Some(99);
+
+        // No focused surface yet: no-ops.
+        assert!(keyboard_key(&mut client, 0, 30, true).is_ok());
+        assert!(keyboard_modifiers(&mut client, 0, 0, 0, 0).is_ok());
+
+        keyboard_enter(&mut client, 1).unwrap();
+        assert!(keyboard_key(&mut client, 1, 30, true).is_ok());
+        assert!(keyboard_modifiers(&mut client, 1, 0, 0, 0).is_ok());
+    }
+}
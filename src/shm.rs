@@ -0,0 +1,330 @@
+// wl_shm: pool/buffer management for software-rendered clients, and the
+// wl_surface.attach/commit path that reads pixels back out of them.
+//
+// A pool's backing fd arrives as SCM_RIGHTS ancillary data on
+// `wl_shm.create_pool` (see `Client::recv_exact_with_fds` and
+// `crate::posix`), is mmap'd once, and buffers are just `(offset, width,
+// height, stride, format)` windows into that mapping.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{FromRawFd, RawFd};
+
+use crate::client::Client;
+use crate::posix;
+use crate::registry::Handler;
+use crate::wire::{Argument, ArgumentType, Message, MessageDesc};
+
+const WL_SHM_CREATE_POOL: u16 = 0;
+const WL_SHM_POOL_CREATE_BUFFER: u16 = 0;
+const WL_SURFACE_ATTACH: u16 = 1;
+
+const WL_SHM_FORMAT_EVENT: u16 = 0;
+const WL_SHM_FORMAT_ARGB8888: u32 = 0;
+const WL_SHM_FORMAT_XRGB8888: u32 = 1;
+
+const CREATE_POOL_SIG: MessageDesc = MessageDesc {
+    name: "wl_shm.create_pool",
+    signature: &[ArgumentType::NewId, ArgumentType::Fd, ArgumentType::Int],
+};
+const CREATE_BUFFER_SIG: MessageDesc = MessageDesc {
+    name: "wl_shm_pool.create_buffer",
+    signature: &[
+        ArgumentType::NewId,
+        ArgumentType::Int,
+        ArgumentType::Int,
+        ArgumentType::Int,
+        ArgumentType::Int,
+        ArgumentType::Uint,
+    ],
+};
+const ATTACH_SIG: MessageDesc = MessageDesc {
+    name: "wl_surface.attach",
+    signature: &[ArgumentType::Object, ArgumentType::Int, ArgumentType::Int],
+};
+
+/// A `wl_shm_pool`'s mapping. Unmapped on drop.
+pub struct ShmPool {
+    // Keeps the pool fd open (and closed on drop); never read directly.
+    _file: File,
+    data: *mut u8,
+    size: usize,
+}
+
+impl Drop for ShmPool {
+    fn drop(&mut self) {
+        posix::munmap_region(self.data, self.size);
+    }
+}
+
+/// A `wl_buffer` backed by a `wl_shm_pool`.
+pub struct ShmBuffer {
+    pub pool: u32,
+    pub offset: i32,
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    pub format: u32,
+}
+
+/// Register this module's request handlers into the shared dispatch table.
+pub fn register(table: &mut HashMap<(String, u16), Handler>) {
+    table.insert(("wl_shm".to_string(), WL_SHM_CREATE_POOL), create_pool);
+    table.insert(
+        ("wl_shm_pool".to_string(), WL_SHM_POOL_CREATE_BUFFER),
+        create_buffer,
+    );
+    table.insert(("wl_surface".to_string(), WL_SURFACE_ATTACH), attach);
+}
+
+/// Send the `wl_shm.format` events a newly bound `wl_shm` object must
+/// receive, advertising the pixel formats this compositor accepts.
+pub fn send_formats(client: &mut Client, shm_id: u32) -> io::Result<()> {
+    for format in [WL_SHM_FORMAT_ARGB8888, WL_SHM_FORMAT_XRGB8888] {
+        client.send_event(&Message {
+            sender_id: shm_id,
+            opcode: WL_SHM_FORMAT_EVENT,
+            args: vec![Argument::Uint(format)],
+        })?;
+    }
+    Ok(())
+}
+
+fn create_pool(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &CREATE_POOL_SIG, fds)?;
+    let (new_id, fd, size) = match (&msg.args[0], &msg.args[1], &msg.args[2]) {
+        (Argument::NewId(id), Argument::Fd(fd), Argument::Int(size)) => (*id, *fd, *size),
+        _ => unreachable!(),
+    };
+    if size <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "wl_shm.create_pool: non-positive size",
+        ));
+    }
+    let size = size as usize;
+
+    let data = posix::mmap_shared_readonly(fd, size)?;
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    client.create_object(new_id, "wl_shm_pool");
+    client.shm_pools.insert(
+        new_id,
+        ShmPool {
+            _file: file,
+            data,
+            size,
+        },
+    );
+    Ok(())
+}
+
+fn create_buffer(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &CREATE_BUFFER_SIG, fds)?;
+    let (new_id, offset, width, height, stride, format) = match (
+        &msg.args[0],
+        &msg.args[1],
+        &msg.args[2],
+        &msg.args[3],
+        &msg.args[4],
+        &msg.args[5],
+    ) {
+        (
+            Argument::NewId(id),
+            Argument::Int(offset),
+            Argument::Int(width),
+            Argument::Int(height),
+            Argument::Int(stride),
+            Argument::Uint(format),
+        ) => (*id, *offset, *width, *height, *stride, *format),
+        _ => unreachable!(),
+    };
+
+    if offset < 0 || width < 0 || height < 0 || stride < 0 {
+        return Err(client.protocol_error(
+            sender_id,
+            crate::client::WL_DISPLAY_ERROR_INVALID_METHOD,
+            format!(
+                "wl_shm_pool.create_buffer: offset/width/height/stride must be non-negative \
+                 (got offset={}, width={}, height={}, stride={})",
+                offset, width, height, stride
+            ),
+        ));
+    }
+    let pool_size = client.shm_pools.get(&sender_id).map(|pool| pool.size);
+    let Some(pool_size) = pool_size else {
+        return Err(client.protocol_error(
+            sender_id,
+            crate::client::WL_DISPLAY_ERROR_INVALID_OBJECT,
+            format!("wl_shm_pool.create_buffer: {} is not a pool", sender_id),
+        ));
+    };
+    let end = (offset as usize)
+        .checked_add((stride as usize) * (height as usize))
+        .filter(|&end| end <= pool_size);
+    if end.is_none() {
+        return Err(client.protocol_error(
+            sender_id,
+            crate::client::WL_DISPLAY_ERROR_INVALID_METHOD,
+            format!(
+                "wl_shm_pool.create_buffer: offset {} + stride {} * height {} exceeds pool size {}",
+                offset, stride, height, pool_size
+            ),
+        ));
+    }
+
+    client.create_object(new_id, "wl_buffer");
+    client.shm_buffers.insert(
+        new_id,
+        ShmBuffer {
+            pool: sender_id,
+            offset,
+            width,
+            height,
+            stride,
+            format,
+        },
+    );
+    Ok(())
+}
+
+fn attach(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &ATTACH_SIG, fds)?;
+    let buffer_id = match msg.args[0] {
+        Argument::Object(id) => id,
+        _ => unreachable!(),
+    };
+
+    if let Some(surface) = client.surfaces.get_mut(&sender_id) {
+        surface.pending_buffer = if buffer_id == 0 { None } else { Some(buffer_id) };
+    }
+    Ok(())
+}
+
+/// The pixels of `surface`'s currently committed buffer, read straight out
+/// of its pool's mapping, if one has been attached and committed yet.
+pub fn committed_pixels(client: &Client, surface_id: u32) -> Option<&[u8]> {
+    let surface = client.surfaces.get(&surface_id)?;
+    let buffer_id = surface.committed_buffer?;
+    let buffer = client.shm_buffers.get(&buffer_id)?;
+    let pool = client.shm_pools.get(&buffer.pool)?;
+
+    let len = (buffer.stride as usize) * (buffer.height as usize);
+    let start = buffer.offset as usize;
+    if start.checked_add(len)? > pool.size {
+        return None;
+    }
+    Some(unsafe { std::slice::from_raw_parts(pool.data.add(start), len) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    fn test_client() -> (Client, UnixStream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let a = unsafe { std::fs::File::from_raw_fd(a.into_raw_fd()) };
+        (Client::new(a), b)
+    }
+
+    /// Register a pool of `size` bytes (no real mapping backs it — these
+    /// tests only exercise `create_buffer`'s bounds checks, never the
+    /// mapping itself) under `pool_id` on `client`.
+    fn insert_pool(client: &mut Client, pool_id: u32, size: usize) {
+        client.create_object(pool_id, "wl_shm_pool");
+        client.shm_pools.insert(
+            pool_id,
+            ShmPool {
+                _file: std::fs::File::open("/dev/null").unwrap(),
+                data: std::ptr::null_mut(),
+                size,
+            },
+        );
+    }
+
+    fn create_buffer_body(offset: i32, width: i32, height: i32, stride: i32, format: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&99u32.to_ne_bytes()); // new_id
+        body.extend_from_slice(&offset.to_ne_bytes());
+        body.extend_from_slice(&width.to_ne_bytes());
+        body.extend_from_slice(&height.to_ne_bytes());
+        body.extend_from_slice(&stride.to_ne_bytes());
+        body.extend_from_slice(&format.to_ne_bytes());
+        body
+    }
+
+    #[test]
+    fn create_buffer_rejects_negative_offset_width_height_stride() {
+        for body in [
+            create_buffer_body(-1, 4, 4, 16, WL_SHM_FORMAT_ARGB8888),
+            create_buffer_body(0, -4, 4, 16, WL_SHM_FORMAT_ARGB8888),
+            create_buffer_body(0, 4, -4, 16, WL_SHM_FORMAT_ARGB8888),
+            create_buffer_body(0, 4, 4, -16, WL_SHM_FORMAT_ARGB8888),
+        ] {
+            let (mut client, _peer) = test_client();
+            insert_pool(&mut client, 1, 4096);
+            let mut fds = VecDeque::new();
+            assert!(create_buffer(&mut client, 1, WL_SHM_POOL_CREATE_BUFFER, &body, &mut fds)
+                .is_err());
+            assert!(!client.shm_buffers.contains_key(&99));
+        }
+    }
+
+    #[test]
+    fn create_buffer_rejects_overflowing_bounds() {
+        let (mut client, _peer) = test_client();
+        insert_pool(&mut client, 1, 4096);
+        // stride * height (about 4.6e18 bytes on 64-bit) is far past the pool size.
+        let body = create_buffer_body(0, 4, i32::MAX, i32::MAX, WL_SHM_FORMAT_ARGB8888);
+        let mut fds = VecDeque::new();
+        assert!(
+            create_buffer(&mut client, 1, WL_SHM_POOL_CREATE_BUFFER, &body, &mut fds).is_err()
+        );
+        assert!(!client.shm_buffers.contains_key(&99));
+    }
+
+    #[test]
+    fn create_buffer_accepts_buffer_at_exact_pool_boundary() {
+        let (mut client, _peer) = test_client();
+        insert_pool(&mut client, 1, 64);
+        // offset 0 + stride 16 * height 4 == pool size 64, exactly.
+        let body = create_buffer_body(0, 4, 4, 16, WL_SHM_FORMAT_ARGB8888);
+        let mut fds = VecDeque::new();
+        create_buffer(&mut client, 1, WL_SHM_POOL_CREATE_BUFFER, &body, &mut fds).unwrap();
+        assert!(client.shm_buffers.contains_key(&99));
+    }
+
+    #[test]
+    fn create_buffer_rejects_buffer_one_byte_over_pool_boundary() {
+        let (mut client, _peer) = test_client();
+        insert_pool(&mut client, 1, 63);
+        // offset 0 + stride 16 * height 4 == 64, one byte over a 63-byte pool.
+        let body = create_buffer_body(0, 4, 4, 16, WL_SHM_FORMAT_ARGB8888);
+        let mut fds = VecDeque::new();
+        assert!(
+            create_buffer(&mut client, 1, WL_SHM_POOL_CREATE_BUFFER, &body, &mut fds).is_err()
+        );
+        assert!(!client.shm_buffers.contains_key(&99));
+    }
+}
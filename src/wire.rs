@@ -0,0 +1,308 @@
+// Wayland wire format: messages, arguments and their (de)serialization.
+//
+// Every request/event is an 8-byte header (sender_id, then size<<16 | opcode)
+// followed by a sequence of arguments whose types are fixed by the
+// interface's signature (see `MessageDesc`).
+
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::RawFd;
+
+/// The type of a single wire argument, as declared in an interface's
+/// request/event signature.
+///
+/// `Fixed` and `Array` round-trip through `Argument` but aren't part of any
+/// request signature this compositor currently decodes (events carrying them
+/// are built from `Argument` directly); kept here because they're part of
+/// the wire format proper, not something we get to omit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum ArgumentType {
+    Int,
+    Uint,
+    Fixed,
+    Str,
+    Object,
+    NewId,
+    Array,
+    Fd,
+}
+
+/// A decoded wire argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Int(i32),
+    Uint(u32),
+    /// 24.8 signed fixed-point, as used by the wire format.
+    Fixed(i32),
+    Str(String),
+    Object(u32),
+    NewId(u32),
+    Array(Vec<u8>),
+    Fd(RawFd),
+}
+
+/// Describes the argument signature of a single request or event opcode.
+pub struct MessageDesc {
+    pub name: &'static str,
+    pub signature: &'static [ArgumentType],
+}
+
+/// Size in bytes of a message's fixed header.
+pub const HEADER_LEN: usize = 8;
+
+/// A message's 8-byte header: the object it targets, the opcode, and the
+/// total message size (header included) the body is expected to fill.
+pub struct Header {
+    pub sender_id: u32,
+    pub opcode: u16,
+    pub size: u32,
+}
+
+impl Header {
+    /// Decode the header fields out of the raw wire bytes. This never
+    /// fails — `size` may still be nonsensical, which `body_len` checks.
+    pub fn parse(bytes: &[u8; HEADER_LEN]) -> Header {
+        let sender_id = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let size_opcode = u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        Header {
+            sender_id,
+            opcode: (size_opcode & 0xFFFF) as u16,
+            size: size_opcode >> 16,
+        }
+    }
+
+    /// The length of the body following this header, once `size` has been
+    /// checked to actually contain one. `size` is packed into the top 16
+    /// bits of the header's second word, so it's already bounded to
+    /// `u16::MAX` — no separate upper-bound check is needed.
+    pub fn body_len(&self) -> Result<usize, String> {
+        if (self.size as usize) < HEADER_LEN {
+            return Err(format!(
+                "message size {} for object {} is smaller than the {}-byte header",
+                self.size, self.sender_id, HEADER_LEN
+            ));
+        }
+        Ok(self.size as usize - HEADER_LEN)
+    }
+}
+
+/// A fully decoded wire message: the object it targets, the opcode, and its
+/// arguments.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub sender_id: u32,
+    pub opcode: u16,
+    pub args: Vec<Argument>,
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+impl Message {
+    /// Decode `body` (the bytes following the 8-byte header) according to
+    /// `desc`. `fds` supplies the file descriptors received out-of-band for
+    /// any `Fd` arguments in the signature.
+    pub fn deserialize(
+        sender_id: u32,
+        opcode: u16,
+        body: &[u8],
+        desc: &MessageDesc,
+        fds: &mut VecDeque<RawFd>,
+    ) -> io::Result<Message> {
+        let mut args = Vec::with_capacity(desc.signature.len());
+        let mut off = 0usize;
+
+        for arg_type in desc.signature {
+            match arg_type {
+                ArgumentType::Int | ArgumentType::Uint | ArgumentType::Fixed
+                | ArgumentType::Object | ArgumentType::NewId => {
+                    let word = read_u32(body, off)?;
+                    off += 4;
+                    args.push(match arg_type {
+                        ArgumentType::Int => Argument::Int(word as i32),
+                        ArgumentType::Uint => Argument::Uint(word),
+                        ArgumentType::Fixed => Argument::Fixed(word as i32),
+                        ArgumentType::Object => Argument::Object(word),
+                        ArgumentType::NewId => Argument::NewId(word),
+                        _ => unreachable!(),
+                    });
+                }
+                ArgumentType::Str => {
+                    let len = read_u32(body, off)? as usize;
+                    off += 4;
+                    if off + len > body.len() || len == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("truncated string argument in {}", desc.name),
+                        ));
+                    }
+                    let bytes = &body[off..off + len - 1]; // drop trailing NUL
+                    let s = String::from_utf8_lossy(bytes).into_owned();
+                    off += pad4(len);
+                    args.push(Argument::Str(s));
+                }
+                ArgumentType::Array => {
+                    let len = read_u32(body, off)? as usize;
+                    off += 4;
+                    if off + len > body.len() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("truncated array argument in {}", desc.name),
+                        ));
+                    }
+                    args.push(Argument::Array(body[off..off + len].to_vec()));
+                    off += pad4(len);
+                }
+                ArgumentType::Fd => {
+                    let fd = fds.pop_front().ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("missing fd argument in {}", desc.name),
+                        )
+                    })?;
+                    args.push(Argument::Fd(fd));
+                }
+            }
+        }
+
+        Ok(Message {
+            sender_id,
+            opcode,
+            args,
+        })
+    }
+
+    /// Encode this message back into wire bytes, back-patching the size
+    /// field in the header once the body length is known.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&self.sender_id.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes()); // size/opcode, patched below
+
+        for arg in &self.args {
+            match arg {
+                Argument::Int(v) => msg.extend_from_slice(&v.to_ne_bytes()),
+                Argument::Uint(v) => msg.extend_from_slice(&v.to_ne_bytes()),
+                Argument::Fixed(v) => msg.extend_from_slice(&v.to_ne_bytes()),
+                Argument::Object(v) => msg.extend_from_slice(&v.to_ne_bytes()),
+                Argument::NewId(v) => msg.extend_from_slice(&v.to_ne_bytes()),
+                Argument::Str(s) => {
+                    let bytes = s.as_bytes();
+                    let len = bytes.len() + 1; // account for trailing NUL
+                    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+                    msg.extend_from_slice(bytes);
+                    msg.push(0);
+                    while msg.len() % 4 != 0 {
+                        msg.push(0);
+                    }
+                }
+                Argument::Array(bytes) => {
+                    msg.extend_from_slice(&(bytes.len() as u32).to_ne_bytes());
+                    msg.extend_from_slice(bytes);
+                    while msg.len() % 4 != 0 {
+                        msg.push(0);
+                    }
+                }
+                // Fds are sent out-of-band (ancillary data); nothing goes in
+                // the body.
+                Argument::Fd(_) => {}
+            }
+        }
+
+        let size = msg.len() as u32;
+        let size_opcode = (size << 16) | self.opcode as u32;
+        msg[4..8].copy_from_slice(&size_opcode.to_ne_bytes());
+
+        msg
+    }
+
+    /// File descriptors carried by this message's arguments, in order.
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.args
+            .iter()
+            .filter_map(|arg| match arg {
+                Argument::Fd(fd) => Some(*fd),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn read_u32(body: &[u8], off: usize) -> io::Result<u32> {
+    if off + 4 > body.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message body too short",
+        ));
+    }
+    Ok(u32::from_ne_bytes([
+        body[off],
+        body[off + 1],
+        body[off + 2],
+        body[off + 3],
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips_through_serialize() {
+        let msg = Message {
+            sender_id: 7,
+            opcode: 3,
+            args: vec![Argument::Uint(42)],
+        };
+        let bytes = msg.serialize();
+        let header = Header::parse(&bytes[0..HEADER_LEN].try_into().unwrap());
+        assert_eq!(header.sender_id, 7);
+        assert_eq!(header.opcode, 3);
+        assert_eq!(header.body_len().unwrap(), bytes.len() - HEADER_LEN);
+    }
+
+    #[test]
+    fn header_rejects_size_smaller_than_itself() {
+        // size=4, opcode=0: a header claiming a total message size smaller
+        // than the header that carries it.
+        let bytes = [0u8, 0, 0, 0, 0, 0, 4, 0];
+        let header = Header::parse(&bytes);
+        assert!(header.body_len().is_err());
+    }
+
+    #[test]
+    fn deserialize_serialize_round_trip() {
+        let desc = MessageDesc {
+            name: "test.message",
+            signature: &[ArgumentType::Uint, ArgumentType::Str, ArgumentType::Int],
+        };
+        let original = Message {
+            sender_id: 1,
+            opcode: 0,
+            args: vec![
+                Argument::Uint(99),
+                Argument::Str("hi".to_string()),
+                Argument::Int(-5),
+            ],
+        };
+        let bytes = original.serialize();
+        let mut fds = VecDeque::new();
+        let decoded =
+            Message::deserialize(1, 0, &bytes[HEADER_LEN..], &desc, &mut fds).unwrap();
+        assert_eq!(decoded.args, original.args);
+    }
+
+    #[test]
+    fn deserialize_rejects_truncated_string() {
+        let desc = MessageDesc {
+            name: "test.message",
+            signature: &[ArgumentType::Str],
+        };
+        // Claims a 100-byte string but the body doesn't contain one.
+        let body = 100u32.to_ne_bytes().to_vec();
+        let mut fds = VecDeque::new();
+        assert!(Message::deserialize(1, 0, &body, &desc, &mut fds).is_err());
+    }
+}
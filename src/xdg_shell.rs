@@ -0,0 +1,414 @@
+// xdg-shell: surfaces, xdg_surface/xdg_toplevel and the configure
+// handshake that maps a client window.
+//
+// The lifecycle is: wl_compositor.create_surface -> xdg_wm_base.
+// get_xdg_surface -> xdg_surface.get_toplevel, at which point the
+// compositor sends an xdg_toplevel.configure followed by an
+// xdg_surface.configure carrying a serial. The surface isn't considered
+// mapped until the client acks that serial and then commits.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+use crate::client::Client;
+use crate::registry::Handler;
+use crate::wire::{Argument, ArgumentType, Message, MessageDesc};
+
+const CREATE_SURFACE_SIG: MessageDesc = MessageDesc {
+    name: "wl_compositor.create_surface",
+    signature: &[ArgumentType::NewId],
+};
+const GET_XDG_SURFACE_SIG: MessageDesc = MessageDesc {
+    name: "xdg_wm_base.get_xdg_surface",
+    signature: &[ArgumentType::NewId, ArgumentType::Object],
+};
+const GET_TOPLEVEL_SIG: MessageDesc = MessageDesc {
+    name: "xdg_surface.get_toplevel",
+    signature: &[ArgumentType::NewId],
+};
+const ACK_CONFIGURE_SIG: MessageDesc = MessageDesc {
+    name: "xdg_surface.ack_configure",
+    signature: &[ArgumentType::Uint],
+};
+const COMMIT_SIG: MessageDesc = MessageDesc {
+    name: "wl_surface.commit",
+    signature: &[],
+};
+const PONG_SIG: MessageDesc = MessageDesc {
+    name: "xdg_wm_base.pong",
+    signature: &[ArgumentType::Uint],
+};
+
+const WL_COMPOSITOR_CREATE_SURFACE: u16 = 0;
+const XDG_WM_BASE_GET_XDG_SURFACE: u16 = 2;
+const XDG_WM_BASE_PONG: u16 = 3;
+const XDG_SURFACE_GET_TOPLEVEL: u16 = 1;
+const XDG_SURFACE_ACK_CONFIGURE: u16 = 4;
+const WL_SURFACE_COMMIT: u16 = 6;
+
+const XDG_TOPLEVEL_CONFIGURE: u16 = 0;
+const XDG_SURFACE_CONFIGURE: u16 = 0;
+const XDG_WM_BASE_PING: u16 = 0;
+
+/// How often a client is pinged, and how long it has to `pong` back
+/// before the connection is considered dead.
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Per-client `xdg_wm_base` liveness state.
+#[derive(Default)]
+pub struct PingState {
+    pub wm_base: Option<u32>,
+    pending_serial: Option<u32>,
+    sent_at: Option<Instant>,
+    last_ping_at: Option<Instant>,
+}
+
+/// Server-side state for a `wl_surface`.
+pub struct Surface {
+    pub xdg_surface: Option<u32>,
+    pub mapped: bool,
+    /// Buffer set by the most recent `attach`, not yet committed.
+    pub pending_buffer: Option<u32>,
+    /// Buffer that took effect on the most recent `commit`.
+    pub committed_buffer: Option<u32>,
+}
+
+/// Server-side state for an `xdg_surface`.
+pub struct XdgSurface {
+    pub wl_surface: u32,
+    pub toplevel: Option<u32>,
+    pub pending_serial: Option<u32>,
+    pub acked: bool,
+}
+
+/// Server-side state for an `xdg_toplevel`.
+pub struct Toplevel {
+    pub xdg_surface: u32,
+}
+
+/// Register this module's request handlers into the shared dispatch table.
+pub fn register(table: &mut HashMap<(String, u16), Handler>) {
+    table.insert(
+        ("wl_compositor".to_string(), WL_COMPOSITOR_CREATE_SURFACE),
+        create_surface,
+    );
+    table.insert(
+        ("xdg_wm_base".to_string(), XDG_WM_BASE_GET_XDG_SURFACE),
+        get_xdg_surface,
+    );
+    table.insert(
+        ("xdg_surface".to_string(), XDG_SURFACE_GET_TOPLEVEL),
+        get_toplevel,
+    );
+    table.insert(
+        ("xdg_surface".to_string(), XDG_SURFACE_ACK_CONFIGURE),
+        ack_configure,
+    );
+    table.insert(("wl_surface".to_string(), WL_SURFACE_COMMIT), commit);
+    table.insert(("xdg_wm_base".to_string(), XDG_WM_BASE_PONG), pong);
+}
+
+fn create_surface(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &CREATE_SURFACE_SIG, fds)?;
+    let new_id = match msg.args[0] {
+        Argument::NewId(id) => id,
+        _ => unreachable!(),
+    };
+    client.create_object(new_id, "wl_surface");
+    client.surfaces.insert(
+        new_id,
+        Surface {
+            xdg_surface: None,
+            mapped: false,
+            pending_buffer: None,
+            committed_buffer: None,
+        },
+    );
+    Ok(())
+}
+
+fn get_xdg_surface(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &GET_XDG_SURFACE_SIG, fds)?;
+    let (new_id, surface_id) = match (&msg.args[0], &msg.args[1]) {
+        (Argument::NewId(new_id), Argument::Object(surface_id)) => (*new_id, *surface_id),
+        _ => unreachable!(),
+    };
+
+    client.create_object(new_id, "xdg_surface");
+    client.xdg_surfaces.insert(
+        new_id,
+        XdgSurface {
+            wl_surface: surface_id,
+            toplevel: None,
+            pending_serial: None,
+            acked: false,
+        },
+    );
+    if let Some(surface) = client.surfaces.get_mut(&surface_id) {
+        surface.xdg_surface = Some(new_id);
+    }
+    Ok(())
+}
+
+fn get_toplevel(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &GET_TOPLEVEL_SIG, fds)?;
+    let new_id = match msg.args[0] {
+        Argument::NewId(id) => id,
+        _ => unreachable!(),
+    };
+
+    client.create_object(new_id, "xdg_toplevel");
+    client.toplevels.insert(
+        new_id,
+        Toplevel {
+            xdg_surface: sender_id,
+        },
+    );
+    if let Some(xdg_surface) = client.xdg_surfaces.get_mut(&sender_id) {
+        xdg_surface.toplevel = Some(new_id);
+    }
+
+    // xdg_toplevel.configure: suggest a size, let the client choose (0x0),
+    // no states set yet.
+    client.send_event(&Message {
+        sender_id: new_id,
+        opcode: XDG_TOPLEVEL_CONFIGURE,
+        args: vec![
+            Argument::Int(0),
+            Argument::Int(0),
+            Argument::Array(Vec::new()),
+        ],
+    })?;
+
+    // xdg_surface.configure: the serial the client must ack before the
+    // surface is considered mapped.
+    let serial = client.next_serial();
+    if let Some(xdg_surface) = client.xdg_surfaces.get_mut(&sender_id) {
+        xdg_surface.pending_serial = Some(serial);
+    }
+    client.send_event(&Message {
+        sender_id,
+        opcode: XDG_SURFACE_CONFIGURE,
+        args: vec![Argument::Uint(serial)],
+    })?;
+
+    Ok(())
+}
+
+fn ack_configure(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &ACK_CONFIGURE_SIG, fds)?;
+    let serial = match msg.args[0] {
+        Argument::Uint(serial) => serial,
+        _ => unreachable!(),
+    };
+
+    if let Some(xdg_surface) = client.xdg_surfaces.get_mut(&sender_id) {
+        if xdg_surface.pending_serial == Some(serial) {
+            xdg_surface.acked = true;
+        } else {
+            eprintln!(
+                "xdg_surface {}: ack_configure serial {} does not match pending {:?}",
+                sender_id, serial, xdg_surface.pending_serial
+            );
+        }
+    }
+    Ok(())
+}
+
+fn commit(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    Message::deserialize(sender_id, opcode, body, &COMMIT_SIG, fds)?;
+
+    let xdg_surface_id = match client.surfaces.get(&sender_id) {
+        Some(surface) => surface.xdg_surface,
+        None => return Ok(()),
+    };
+    let acked = xdg_surface_id
+        .and_then(|id| client.xdg_surfaces.get(&id))
+        .map(|xdg_surface| xdg_surface.acked)
+        .unwrap_or(false);
+
+    let was_mapped = client
+        .surfaces
+        .get(&sender_id)
+        .map(|surface| surface.mapped)
+        .unwrap_or(false);
+    if let Some(surface) = client.surfaces.get_mut(&sender_id) {
+        surface.committed_buffer = surface.pending_buffer;
+        if acked {
+            surface.mapped = true;
+        }
+    }
+
+    // There's no hit-testing against a window stack yet, so the surface
+    // that just became mapped takes both pointer and keyboard focus, same
+    // as a freshly opened window would in a real compositor; with several
+    // toplevels, the most recently mapped one holds focus.
+    if acked && !was_mapped {
+        crate::seat::keyboard_enter(client, sender_id)?;
+        crate::seat::pointer_enter(client, sender_id, 0, 0)?;
+    }
+
+    if let Some(pixels) = crate::shm::committed_pixels(client, sender_id) {
+        let buffer = client
+            .surfaces
+            .get(&sender_id)
+            .and_then(|surface| surface.committed_buffer)
+            .and_then(|buffer_id| client.shm_buffers.get(&buffer_id));
+        match buffer {
+            Some(buffer) => println!(
+                "wl_surface {}: committed {}x{} buffer (format {}), {} bytes mapped",
+                sender_id,
+                buffer.width,
+                buffer.height,
+                buffer.format,
+                pixels.len()
+            ),
+            None => println!(
+                "wl_surface {}: committed buffer, {} bytes mapped",
+                sender_id,
+                pixels.len()
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn pong(
+    client: &mut Client,
+    sender_id: u32,
+    opcode: u16,
+    body: &[u8],
+    fds: &mut VecDeque<RawFd>,
+) -> io::Result<()> {
+    let msg = Message::deserialize(sender_id, opcode, body, &PONG_SIG, fds)?;
+    let serial = match msg.args[0] {
+        Argument::Uint(serial) => serial,
+        _ => unreachable!(),
+    };
+    if client.ping.pending_serial == Some(serial) {
+        client.ping.pending_serial = None;
+        client.ping.sent_at = None;
+    }
+    Ok(())
+}
+
+/// If this client has bound `xdg_wm_base` and isn't already waiting on a
+/// ping, and at least `PING_INTERVAL` has passed since its last ping (or it
+/// has never been pinged), send one.
+pub fn maybe_ping(client: &mut Client) -> io::Result<()> {
+    let Some(wm_base) = client.ping.wm_base else {
+        return Ok(());
+    };
+    if client.ping.pending_serial.is_some() {
+        return Ok(());
+    }
+    if let Some(last) = client.ping.last_ping_at {
+        if last.elapsed() < PING_INTERVAL {
+            return Ok(());
+        }
+    }
+
+    let serial = client.next_serial();
+    client.ping.pending_serial = Some(serial);
+    let now = Instant::now();
+    client.ping.sent_at = Some(now);
+    client.ping.last_ping_at = Some(now);
+    client.send_event(&Message {
+        sender_id: wm_base,
+        opcode: XDG_WM_BASE_PING,
+        args: vec![Argument::Uint(serial)],
+    })
+}
+
+/// Whether a ping was sent more than `PING_TIMEOUT` ago without a matching
+/// `pong`, i.e. the client should be disconnected as unresponsive.
+pub fn ping_timed_out(client: &Client) -> bool {
+    client
+        .ping
+        .sent_at
+        .is_some_and(|sent_at| sent_at.elapsed() >= PING_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    fn test_client() -> (Client, UnixStream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let a = unsafe { std::fs::File::from_raw_fd(a.into_raw_fd()) };
+        (Client::new(a), b)
+    }
+
+    #[test]
+    fn ack_configure_ignores_mismatched_serial() {
+        let (mut client, _peer) = test_client();
+        client.xdg_surfaces.insert(
+            10,
+            XdgSurface {
+                wl_surface: 1,
+                toplevel: None,
+                pending_serial: Some(5),
+                acked: false,
+            },
+        );
+
+        let body = 6u32.to_ne_bytes();
+        ack_configure(&mut client, 10, XDG_SURFACE_ACK_CONFIGURE, &body, &mut VecDeque::new())
+            .unwrap();
+        assert!(!client.xdg_surfaces.get(&10).unwrap().acked);
+    }
+
+    #[test]
+    fn ack_configure_accepts_matching_serial() {
+        let (mut client, _peer) = test_client();
+        client.xdg_surfaces.insert(
+            10,
+            XdgSurface {
+                wl_surface: 1,
+                toplevel: None,
+                pending_serial: Some(5),
+                acked: false,
+            },
+        );
+
+        let body = 5u32.to_ne_bytes();
+        ack_configure(&mut client, 10, XDG_SURFACE_ACK_CONFIGURE, &body, &mut VecDeque::new())
+            .unwrap();
+        assert!(client.xdg_surfaces.get(&10).unwrap().acked);
+    }
+}